@@ -0,0 +1,277 @@
+#!/usr/bin/env python3
+"""A small local HTTP server used by integration.sh.
+
+Usage: server.py [[host:]port] [certfile keyfile [tls-max=<version>] [alpn=<protocols>]]
+
+It listens on 127.0.0.1 unless given a host, such as "[::]" for IPv6. With a certificate it serves HTTPS, optionally capped at a TLS version such as
+1.2 and advertising a comma-separated list of ALPN protocols.
+
+It serves a handful of deterministic endpoints so tests don't depend on
+the behaviour (or availability) of public services:
+
+  /echo                 reply with the raw request (request line, headers, body)
+  /raw                  reply with the request line and headers byte for byte as
+                        received, before any parsing trims them
+  /redirect?to=<url>    redirect to <url>; optional `status` and `body` params
+  /status/<code>?<k>=<v>...
+                        reply with the given status code and response headers
+  /headers?<k>=<v>...   reply with the given response headers (repeatable); %XX
+                        escapes in values are sent as raw bytes
+  /big-headers?count=<n>&size=<bytes>
+                        reply with <n> headers X-Big-1... of <bytes> bytes each,
+                        for header blocks too large to ask for in a URL
+  /delay?s=<secs>       wait before replying
+  /body?type=<type>&text=<text>
+                        reply with the given body and Content-Type
+  /files/<name>?type=<type>&text=<text>
+                        like /body, under a path naming a file
+  /gzip?status=<code>&text=<text>
+                        reply with the given status and a gzip-encoded body
+  /range?text=<text>    reply with <text>, or the part asked for by a "bytes=a-b"
+                        or "bytes=a-" Range header as a 206; `size=<bytes>` instead
+                        serves that many bytes of the /large pattern, and
+                        `fail-from=<offset>` answers ranges starting there or later
+                        with a 500; `delay=<secs>` holds each range back, counting
+                        the ranges in flight at once under `name=<name>`
+  /peak?name=<name>     reply with the most /range requests named <name> that were
+                        in flight at once
+  /large?size=<bytes>   reply with <bytes> bytes of a repeating pattern, streamed
+  /truncated?length=<n>&text=<text>
+                        declare a Content-Length of <n> but send only <text>,
+                        then close the connection
+  /peer                 reply with the client's address, to tell connections apart
+  /http09               reply with a bare HTTP/0.9 body: no status line, no headers
+  /tls                  reply with the negotiated ALPN protocol and the SNI
+                        server name the client sent (each "none" if absent)
+  /cached?max-age=<n>&etag=<tag>
+                        count requests to this URL and reply with the count, as
+                        cacheable; a matching If-None-Match gets a 304
+  /connects             reply with the target of each CONNECT so far, one per line
+
+It also acts as an HTTP proxy: a CONNECT request opens a tunnel to its
+target, and a request for an absolute URL is served as if it were local.
+"""
+
+import gzip
+import socket
+import ssl
+import sys
+import threading
+import time
+from http.server import BaseHTTPRequestHandler, ThreadingHTTPServer
+from urllib.parse import parse_qsl, urlsplit
+
+
+class RecordingReader:
+    """Wraps a request's rfile, keeping every line read from it."""
+
+    def __init__(self, rfile):
+        self.rfile = rfile
+        self.lines = []
+
+    def readline(self, *args):
+        line = self.rfile.readline(*args)
+        self.lines.append(line)
+        return line
+
+    def __getattr__(self, name):
+        return getattr(self.rfile, name)
+
+
+class Handler(BaseHTTPRequestHandler):
+    protocol_version = "HTTP/1.1"
+    hits = {}
+    hits_lock = threading.Lock()
+    in_flight = {}
+    peak = {}
+    connects = []
+
+    def log_message(self, format, *args):
+        pass
+
+    def setup(self):
+        super().setup()
+        self.rfile = RecordingReader(self.rfile)
+
+    def handle_one_request(self):
+        self.rfile.lines = []
+        super().handle_one_request()
+
+    def read_body(self):
+        if self.headers.get("Transfer-Encoding", "").lower() == "chunked":
+            chunks = []
+            while True:
+                size = int(self.rfile.readline().strip(), 16)
+                if size == 0:
+                    self.rfile.readline()
+                    return b"".join(chunks)
+                chunks.append(self.rfile.read(size))
+                self.rfile.readline()
+        length = int(self.headers.get("Content-Length", 0))
+        return self.rfile.read(length)
+
+    def reply(self, status, body=b"", headers=()):
+        self.send_response(status)
+        for name, value in headers:
+            self.send_header(name, value)
+        self.send_header("Content-Length", str(len(body)))
+        self.end_headers()
+        if self.command != "HEAD":
+            self.wfile.write(body)
+
+    def tunnel(self):
+        with self.hits_lock:
+            self.connects.append(self.path)
+        host, _, port = self.path.rpartition(":")
+        upstream = socket.create_connection((host.strip("[]"), int(port)))
+        self.wfile.write(b"HTTP/1.1 200 Connection established\r\n\r\n")
+        self.wfile.flush()
+
+        def forward(source, target):
+            try:
+                while data := source.recv(65536):
+                    target.sendall(data)
+                target.shutdown(socket.SHUT_WR)
+            except OSError:
+                pass
+
+        back = threading.Thread(target=forward, args=(upstream, self.connection))
+        back.start()
+        forward(self.connection, upstream)
+        back.join()
+        upstream.close()
+        self.close_connection = True
+
+    def handle_any(self):
+        if self.command == "CONNECT":
+            return self.tunnel()
+        url = urlsplit(self.path)
+        query = parse_qsl(url.query, keep_blank_values=True)
+        params = dict(query)
+        body = self.read_body()
+
+        if url.path == "/echo":
+            raw = self.requestline + "\r\n" + str(self.headers)
+            self.reply(200, raw.encode("latin-1") + body, [("Content-Type", "text/plain")])
+        elif url.path == "/raw":
+            head = self.rfile.lines[: self.rfile.lines.index(b"\r\n")]
+            self.reply(200, b"".join(head), [("Content-Type", "text/plain")])
+        elif url.path == "/redirect":
+            status = int(params.get("status", 302))
+            self.reply(
+                status,
+                params.get("body", "").encode(),
+                [("Location", params["to"]), ("Content-Type", "text/plain")],
+            )
+        elif url.path.startswith("/status/"):
+            self.reply(int(url.path.split("/")[2]), b"status body\n", query)
+        elif url.path == "/headers":
+            # Decoded as Latin-1 so each %XX goes on the wire as that exact byte.
+            self.reply(200, b"headers\n", parse_qsl(url.query, keep_blank_values=True, encoding="latin-1"))
+        elif url.path == "/big-headers":
+            value = "a" * int(params.get("size", 10))
+            self.reply(200, b"headers\n", [("X-Big-%d" % i, value) for i in range(1, int(params.get("count", 1)) + 1)])
+        elif url.path == "/gzip":
+            self.reply(
+                int(params.get("status", 200)),
+                gzip.compress(params.get("text", "").encode()),
+                [("Content-Encoding", "gzip"), ("Content-Type", "text/plain")],
+            )
+        elif url.path == "/range":
+            text = params.get("text", "").encode()
+            if "size" in params:
+                text = (bytes(range(256)) * (int(params["size"]) // 256 + 1))[: int(params["size"])]
+            requested = self.headers.get("Range", "")
+            if requested.startswith("bytes="):
+                start, end = requested[len("bytes="):].split("-")
+                start, end = int(start), int(end) if end else len(text) - 1
+                if start >= int(params.get("fail-from", len(text))):
+                    self.reply(500, b"range refused")
+                    return
+                if "delay" in params:
+                    name = params.get("name", "")
+                    with self.hits_lock:
+                        self.in_flight[name] = self.in_flight.get(name, 0) + 1
+                        self.peak[name] = max(self.peak.get(name, 0), self.in_flight[name])
+                    time.sleep(float(params["delay"]))
+                    with self.hits_lock:
+                        self.in_flight[name] -= 1
+                self.reply(
+                    206,
+                    text[start : end + 1],
+                    [("Content-Range", "bytes %d-%d/%d" % (start, end, len(text))), ("Accept-Ranges", "bytes")],
+                )
+            else:
+                self.reply(200, text, [("Accept-Ranges", "bytes")])
+        elif url.path == "/large":
+            size = int(params.get("size", 0))
+            chunk = bytes(range(256)) * 256
+            self.send_response(200)
+            self.send_header("Content-Length", str(size))
+            self.end_headers()
+            while size > 0:
+                self.wfile.write(chunk[:size])
+                size -= len(chunk)
+        elif url.path == "/truncated":
+            self.send_response(200)
+            self.send_header("Content-Length", params.get("length", "100"))
+            self.end_headers()
+            self.wfile.write(params.get("text", "partial").encode())
+            self.close_connection = True
+        elif url.path == "/peak":
+            self.reply(200, b"%d\n" % self.peak.get(params.get("name", ""), 0))
+        elif url.path == "/peer":
+            self.reply(200, ("%s:%d\n" % self.client_address[:2]).encode())
+        elif url.path == "/http09":
+            # The body alone; the end of the connection marks its end.
+            self.wfile.write(b"legacy body\n")
+            self.close_connection = True
+        elif url.path == "/tls":
+            alpn = self.connection.selected_alpn_protocol() or "none"
+            sni = getattr(self.connection, "sni", None) or "none"
+            self.reply(200, ("alpn: %s\nsni: %s\n" % (alpn, sni)).encode())
+        elif url.path == "/cached":
+            with self.hits_lock:
+                hits = self.hits[self.path] = self.hits.get(self.path, 0) + 1
+            etag = '"%s"' % params.get("etag", "v1")
+            headers = [("ETag", etag), ("Cache-Control", "max-age=%s" % params.get("max-age", "60"))]
+            if self.headers.get("If-None-Match") == etag:
+                self.reply(304, b"", headers)
+            else:
+                self.reply(200, b"request %d\n" % hits, headers)
+        elif url.path == "/connects":
+            with self.hits_lock:
+                targets = "".join(target + "\n" for target in self.connects)
+            self.reply(200, targets.encode())
+        elif url.path == "/body" or url.path.startswith("/files/"):
+            self.reply(200, params.get("text", "").encode(), [("Content-Type", params.get("type", "text/plain"))])
+        elif url.path == "/delay":
+            time.sleep(float(params.get("s", 1)))
+            self.reply(200, b"delayed\n")
+        else:
+            self.reply(404, b"not found\n")
+
+    def __getattr__(self, name):
+        # Route every method (including custom ones) through handle_any.
+        if name.startswith("do_"):
+            return self.handle_any
+        raise AttributeError(name)
+
+
+if __name__ == "__main__":
+    host, _, port = (sys.argv[1] if len(sys.argv) > 1 else "18080").rpartition(":")
+    host = host.strip("[]") or "127.0.0.1"
+    if ":" in host:
+        ThreadingHTTPServer.address_family = socket.AF_INET6
+    server = ThreadingHTTPServer((host, int(port)), Handler)
+    if len(sys.argv) > 3:
+        context = ssl.SSLContext(ssl.PROTOCOL_TLS_SERVER)
+        context.load_cert_chain(sys.argv[2], sys.argv[3])
+        context.sni_callback = lambda sock, name, _: setattr(sock, "sni", name)
+        options = dict(arg.split("=", 1) for arg in sys.argv[4:])
+        if "tls-max" in options:
+            context.maximum_version = ssl.TLSVersion["TLSv" + options["tls-max"].replace(".", "_")]
+        if "alpn" in options:
+            context.set_alpn_protocols(options["alpn"].split(","))
+        server.socket = context.wrap_socket(server.socket, server_side=True)
+    server.serve_forever()